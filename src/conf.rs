@@ -0,0 +1,17 @@
+//! Configuration types used when setting up `ggez`'s subsystems.
+
+/// The number of samples used for multisample anti-aliasing.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NumSamples {
+    /// No multisampling.
+    One = 1,
+    /// Two samples per pixel.
+    Two = 2,
+    /// Four samples per pixel.
+    Four = 4,
+    /// Eight samples per pixel.
+    Eight = 8,
+    /// Sixteen samples per pixel.
+    Sixteen = 16,
+}
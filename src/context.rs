@@ -0,0 +1,10 @@
+//! The `Context` is `ggez`'s top-level handle to all engine state.
+
+use graphics::GraphicsContext;
+
+/// The `Context` holds the state needed to interface with the hardware.
+/// The graphics subsystem is the only one it holds state for.
+pub struct Context {
+    /// State for the graphics subsystem.
+    pub gfx_context: GraphicsContext,
+}
@@ -0,0 +1,50 @@
+//! Error types for `ggez`.
+
+use std::error::Error;
+use std::fmt;
+
+use gfx;
+
+/// An error that can occur while using `ggez`.
+#[derive(Debug)]
+pub enum GameError {
+    /// An error occurred in the renderer.
+    RenderError(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GameError::RenderError(ref s) => write!(f, "Render error: {}", s),
+        }
+    }
+}
+
+impl Error for GameError {}
+
+impl From<gfx::CombinedError> for GameError {
+    fn from(e: gfx::CombinedError) -> GameError {
+        GameError::RenderError(format!("{}", e))
+    }
+}
+
+impl From<gfx::ResourceViewError> for GameError {
+    fn from(e: gfx::ResourceViewError) -> GameError {
+        GameError::RenderError(format!("{}", e))
+    }
+}
+
+impl From<gfx::TargetViewError> for GameError {
+    fn from(e: gfx::TargetViewError) -> GameError {
+        GameError::RenderError(format!("{}", e))
+    }
+}
+
+impl From<gfx::texture::CreationError> for GameError {
+    fn from(e: gfx::texture::CreationError) -> GameError {
+        GameError::RenderError(format!("{}", e))
+    }
+}
+
+/// A convenient result type consisting of a return type and a `GameError`.
+pub type GameResult<T = ()> = Result<T, GameError>;
@@ -2,51 +2,186 @@
 //! the screen.  This allows graphics to be rendered to images off-screen
 //! in order to do things like saving to an image file or creating cool effects.
 
+use std::fmt;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
 use gfx::{Factory};
-use gfx::format::{ChannelTyped, Srgb, Srgba8, Swizzle};
-use gfx::handle::RenderTargetView;
-use gfx::memory::{Bind, Usage};
+use gfx::format::{Formatted, R16, R8, RenderFormat, Rgba16F, Rgba32F, Srgba8, SurfaceTyped, Swizzle, TextureFormat, Unorm};
+use gfx::handle::{RenderTargetView, ShaderResourceView};
+use gfx::memory::{Bind, Typed, Usage};
 use gfx::texture::{AaMode, Kind};
+use gfx::traits::FactoryExt;
+
+use image;
 
 use Context;
 use conf::*;
 use error::*;
 use graphics::*;
+use graphics::commands::{CanvasRef, CommandHandler, CommandList};
+
+/// The pixel format a `Canvas` renders into.
+///
+/// `Srgba8` is the default and matches the screen's own format. The other
+/// variants exist for off-screen passes that need more precision than
+/// 8-bit sRGB (`Rgba16F`/`Rgba32F`, e.g. for HDR/bloom/tonemapping) or only
+/// a single channel (`R8`/`R16`, e.g. for depth-style effects or masks).
+///
+/// Only `Srgba8` canvases can be bound as a render target (`set_canvas`,
+/// `push_canvas`, `Canvas::execute`, `Command::SetCanvas`); binding a canvas
+/// of any other format returns a `GameError::RenderError`. Canvases of the
+/// other formats can be created and read back, but not drawn into.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CanvasFormat {
+    Srgba8,
+    Rgba16F,
+    Rgba32F,
+    R8,
+    R16,
+}
+
+/// A `gfx` color format usable as a `Canvas`'s render target and backing
+/// texture. Implemented for the handful of formats `CanvasFormat` exposes;
+/// you should not need to implement it yourself.
+pub trait CanvasColorFormat: Formatted + TextureFormat + RenderFormat {
+    /// The runtime `CanvasFormat` this type corresponds to.
+    const CANVAS_FORMAT: CanvasFormat;
+    /// How many color channels this format stores.
+    const CHANNEL_COUNT: u8;
+}
+
+impl CanvasColorFormat for Srgba8 {
+    const CANVAS_FORMAT: CanvasFormat = CanvasFormat::Srgba8;
+    const CHANNEL_COUNT: u8 = 4;
+}
+
+impl CanvasColorFormat for Rgba16F {
+    const CANVAS_FORMAT: CanvasFormat = CanvasFormat::Rgba16F;
+    const CHANNEL_COUNT: u8 = 4;
+}
+
+impl CanvasColorFormat for Rgba32F {
+    const CANVAS_FORMAT: CanvasFormat = CanvasFormat::Rgba32F;
+    const CHANNEL_COUNT: u8 = 4;
+}
+
+impl CanvasColorFormat for (R8, Unorm) {
+    const CANVAS_FORMAT: CanvasFormat = CanvasFormat::R8;
+    const CHANNEL_COUNT: u8 = 1;
+}
+
+impl CanvasColorFormat for (R16, Unorm) {
+    const CANVAS_FORMAT: CanvasFormat = CanvasFormat::R16;
+    const CHANNEL_COUNT: u8 = 1;
+}
+
+/// A `CanvasColorFormat` whose samples can be read back as an `Image`, i.e.
+/// one whose shader-resource view has the same `[f32; 4]` view type `Image`
+/// expects. The single-channel `R8`/`R16` formats don't qualify, so canvases
+/// using them support the raw readback methods but not `get_image` /
+/// `into_inner` / `Drawable`.
+pub trait DrawableCanvasFormat: CanvasColorFormat<View = [f32; 4]> {}
+impl DrawableCanvasFormat for Srgba8 {}
+impl DrawableCanvasFormat for Rgba16F {}
+impl DrawableCanvasFormat for Rgba32F {}
+
+/// The GPU resources backing a `Canvas`: the backing texture's render-target
+/// view and the shader-resource view used to sample it. Bundled behind one
+/// `Arc` so both views move and drop together as a single unit, which is
+/// what makes `CanvasGeneric: Clone` cheap (a refcount bump, not a fresh
+/// `create_texture`). The underlying gfx handles are already individually
+/// reference-counted and free their GPU resources on their own once
+/// unreferenced, `RenderTargetView`/`ShaderResourceView`/`Image` included;
+/// this wrapper doesn't change when that happens, it just lets a `Canvas`
+/// be copied around as one value instead of two separately-cloned views.
+struct CanvasHandle<Spec, CF>
+where
+    Spec: BackendSpec,
+    CF: CanvasColorFormat,
+{
+    target: RenderTargetView<Spec::Resources, CF>,
+    resource: ShaderResourceView<Spec::Resources, CF::View>,
+}
+
+// Implemented by hand rather than derived: `#[derive(Debug)]` would require
+// `CF::View: Debug`, which `CanvasColorFormat` doesn't guarantee.
+impl<Spec, CF> fmt::Debug for CanvasHandle<Spec, CF>
+where
+    Spec: BackendSpec,
+    CF: CanvasColorFormat,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanvasHandle").finish()
+    }
+}
 
 /// A generic canvas independent of graphics backend. This type should probably
 /// never be used directly; use `ggez::graphics::Canvas` instead.
 #[derive(Debug)]
-pub struct CanvasGeneric<Spec>
+pub struct CanvasGeneric<Spec, CF = Srgba8>
+where
+    Spec: BackendSpec,
+    CF: CanvasColorFormat,
+{
+    handle: Arc<CanvasHandle<Spec, CF>>,
+    sampler_info: SamplerInfo,
+    blend_mode: Option<BlendMode>,
+    width: u32,
+    height: u32,
+}
+
+impl<Spec, CF> Clone for CanvasGeneric<Spec, CF>
 where
     Spec: BackendSpec,
+    CF: CanvasColorFormat,
 {
-    target: RenderTargetView<Spec::Resources, Srgba8>,
-    image: Image,
+    fn clone(&self) -> Self {
+        CanvasGeneric {
+            handle: self.handle.clone(),
+            sampler_info: self.sampler_info,
+            blend_mode: self.blend_mode,
+            width: self.width,
+            height: self.height,
+        }
+    }
 }
 
 /// A canvas that can be rendered to instead of the screen (sometimes referred
 /// to as "render target" or "render to texture"). Set the canvas with the
 /// `ggez::graphics::set_canvas()` function, and then anything you
-/// draw will be drawn to the canvas instead of the screen.  
+/// draw will be drawn to the canvas instead of the screen.
 ///
 /// Resume drawing to the screen by calling `ggez::graphics::set_canvas(None)`.
 pub type Canvas = CanvasGeneric<GlBackendSpec>;
 
-impl Canvas {
-    /// Create a new canvas with the given size and number of samples.
-    pub fn new(
+impl<CF> CanvasGeneric<GlBackendSpec, CF>
+where
+    CF: CanvasColorFormat,
+{
+    /// Create a new canvas with the given size, number of samples and pixel
+    /// format. See `CanvasFormat` for the available formats; `format` must
+    /// match the `CF` type parameter, which is checked in debug builds.
+    pub fn new_with_format(
         ctx: &mut Context,
         width: u32,
         height: u32,
         samples: NumSamples,
-    ) -> GameResult<Canvas> {
+        format: CanvasFormat,
+    ) -> GameResult<Self> {
+        debug_assert_eq!(
+            CF::CANVAS_FORMAT,
+            format,
+            "CanvasFormat argument must match the CF type parameter"
+        );
         let (w, h) = (width as u16, height as u16);
         let aa = match samples {
             NumSamples::One => AaMode::Single,
             s => AaMode::Multi(s as u8),
         };
         let kind = Kind::D2(w, h, aa);
-        let cty = Srgb::get_channel_type();
+        let cty = CF::get_format().1;
         let levels = 1;
         let factory = &mut ctx.gfx_context.factory;
         let tex = factory.create_texture(
@@ -56,24 +191,140 @@ impl Canvas {
             Usage::Data,
             Some(cty),
         )?;
-        let resource = factory.view_texture_as_shader_resource::<Srgba8>(
+        let resource = factory.view_texture_as_shader_resource::<CF>(
             &tex,
             (0, levels - 1),
             Swizzle::new(),
         )?;
         let target = factory.view_texture_as_render_target(&tex, 0, None)?;
-        Ok(Canvas {
-            target,
-            image: Image {
-                texture: resource,
-                sampler_info: ctx.gfx_context.default_sampler_info,
-                blend_mode: None,
-                width,
-                height,
-            },
+        Ok(CanvasGeneric {
+            handle: Arc::new(CanvasHandle { target, resource }),
+            sampler_info: ctx.gfx_context.default_sampler_info,
+            blend_mode: None,
+            width,
+            height,
         })
     }
 
+    /// Dumps the `Canvas`'s render target into a `Vec` of pixel data, in
+    /// `CF`'s own surface format (for an `Srgba8` canvas, `Canvas::to_rgba8`
+    /// returns the same data flattened into RGBA8 bytes).
+    ///
+    /// Because OpenGL's origin is in the bottom-left rather than the
+    /// top-left, the rows read back from the GPU are bottom-up; this
+    /// re-orders them so the returned buffer is top-down, matching the
+    /// layout the `image` crate (and most image formats) expect.
+    pub fn to_surface_data(&self, ctx: &mut Context) -> GameResult<Vec<<CF::Surface as SurfaceTyped>::DataType>>
+    where
+        CF::Surface: SurfaceTyped,
+        <CF::Surface as SurfaceTyped>::DataType: Copy,
+    {
+        let (w, h) = (self.width, self.height);
+        let gfx = &mut ctx.gfx_context;
+
+        let dl_buffer = gfx
+            .factory
+            .create_download_buffer::<<CF::Surface as SurfaceTyped>::DataType>(w as usize * h as usize)
+            .map_err(|e| GameError::RenderError(format!("{}", e)))?;
+
+        gfx.encoder
+            .copy_texture_to_buffer_raw(
+                self.handle.target.raw().get_texture(),
+                None,
+                gfx::texture::RawImageInfo {
+                    xoffset: 0,
+                    yoffset: 0,
+                    zoffset: 0,
+                    width: w as u16,
+                    height: h as u16,
+                    depth: 0,
+                    format: CF::get_format(),
+                    mipmap: 0,
+                },
+                dl_buffer.raw(),
+                0,
+            )
+            .map_err(|e| GameError::RenderError(format!("{:?}", e)))?;
+        gfx.encoder.flush(&mut *gfx.device);
+
+        let reader = gfx
+            .factory
+            .read_mapping(&dl_buffer)
+            .map_err(|e| GameError::RenderError(format!("{}", e)))?;
+
+        // Intermediary buffer to avoid casting, and to flip the rows
+        // top-to-bottom so the image isn't upside-down.
+        let mut data = Vec::with_capacity(w as usize * h as usize);
+        for row in reader.chunks(w as usize).rev() {
+            data.extend_from_slice(row);
+        }
+        Ok(data)
+    }
+
+    /// A clone of this canvas's render target with its format erased, for
+    /// `CanvasRef` / `Command::SetCanvas`.
+    pub(crate) fn raw_target(&self) -> gfx::handle::RawRenderTargetView<<GlBackendSpec as BackendSpec>::Resources> {
+        self.handle.target.raw().clone()
+    }
+
+    /// Replay a recorded `CommandList` against this canvas. A `SetCanvas`
+    /// command inside the list temporarily switches to a different target;
+    /// once the whole list has run, the target that was bound before this
+    /// call is restored, regardless of what the list itself bound last.
+    pub fn execute(&self, ctx: &mut Context, commands: &CommandList) -> GameResult<()> {
+        let previous = ctx.gfx_context.data.out.clone();
+        let saved_stack = mem::replace(&mut ctx.gfx_context.canvas_stack, RenderTargetStack::new());
+        let mut result = set_canvas(ctx, Some(self));
+        if result.is_ok() {
+            result = commands
+                .iter()
+                .map(|command| ctx.handle_command(command))
+                .collect();
+        }
+        ctx.gfx_context.canvas_stack = saved_stack;
+        ctx.gfx_context.data.out = previous;
+        result
+    }
+}
+
+impl<CF> CanvasGeneric<GlBackendSpec, CF>
+where
+    CF: DrawableCanvasFormat,
+{
+    /// Gets the backend `Image` that is being rendered to.
+    ///
+    /// `Image` has no render-target half to share, so this clones out just
+    /// the shader-resource view (itself independently `Arc`-backed by gfx)
+    /// rather than handing out the `Canvas`'s own `CanvasHandle` — the two
+    /// don't share one handle, they each keep the GPU texture alive on
+    /// their own.
+    pub fn get_image(&self) -> Image {
+        Image {
+            texture: self.handle.resource.clone(),
+            sampler_info: self.sampler_info,
+            blend_mode: self.blend_mode,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Destroys the Canvas and returns the `Image` it contains.
+    pub fn into_inner(self) -> Image {
+        self.get_image()
+    }
+}
+
+impl Canvas {
+    /// Create a new canvas with the given size and number of samples.
+    pub fn new(
+        ctx: &mut Context,
+        width: u32,
+        height: u32,
+        samples: NumSamples,
+    ) -> GameResult<Canvas> {
+        Canvas::new_with_format(ctx, width, height, samples, CanvasFormat::Srgba8)
+    }
+
     /// Create a new canvas with the current window dimensions.
     pub fn with_window_size(ctx: &mut Context) -> GameResult<Canvas> {
         use graphics;
@@ -82,87 +333,118 @@ impl Canvas {
         Canvas::new(ctx, w, h, NumSamples::One)
     }
 
-    /// Gets the backend `Image` that is being rendered to.
-    pub fn get_image(&self) -> &Image {
-        &self.image
+    /// Dumps the `Canvas`'s render target into a `Vec` of RGBA8 pixel data.
+    pub fn to_rgba8(&self, ctx: &mut Context) -> GameResult<Vec<u8>> {
+        let data = self.to_surface_data(ctx)?;
+        let mut out = Vec::with_capacity(data.len() * 4);
+        for pixel in &data {
+            out.extend(pixel);
+        }
+        Ok(out)
     }
 
-    /// Destroys the Canvas and returns the `Image` it contains.
-    pub fn into_inner(self) -> Image {
-        // This texture is created with different settings
-        // than the default; does that matter?
-        self.image
-    }
-
-    /*
-    /// Exports the canvas to an image on your hard disk
-    pub fn save_to_png(&self, path: &str) {
-        use gfx::memory::Typed;
-        use gfx::format::Formatted;
-        use gfx::format::SurfaceTyped;
-
-        let (w, h) = (self.image.width, self.image.height);
-        let buffer: String = self.image.texture.raw();
-        type SurfaceData = <<ColorFormat as Formatted>::Surface as SurfaceTyped>::DataType;
-        // TODO unwrap and move this?
-        let dl_buffer = gfx.factory.create_download_buffer::<SurfaceData>(w as usize * h as usize).unwrap();
-        // TODO UNWRAP
-        gfx.encoder.copy_texture_to_buffer_raw(
-            gfx.data.out.raw().get_texture(),
-            None,
-            gfx::texture::RawImageInfo {
-                        xoffset: 0,
-                        yoffset: 0,
-                        zoffset: 0,
-                        width: w as u16,
-                        height: h as u16,
-                        depth: 0,
-                        format: ColorFormat::get_format(),
-                        mipmap: 0,
-            },
-            dl_buffer.raw(),
-            0
-        ).unwrap();
-        gfx.encoder.flush(&mut *gfx.device);
-
-        // TODO unwrap
-        let reader = gfx.factory.read_mapping(&dl_buffer).unwrap();
-        // intermediary buffer to avoid casting (according to gfx example)
-        // and also to reverse the order in which we pass the rows
-        // so the screenshot isn't upside-down
-        let mut data = Vec::with_capacity(w as usize * h as usize * 4);
-        for row in reader.chunks(w as usize).rev() {
-            for pixel in row.iter() {
-                data.extend(pixel);
-            }
-        }
-        // TODO unwrap
-        image::save_buffer(path, &data, w as u32, h as u32, image::ColorType::RGBA(8)).unwrap();
-    } */
+    /// Exports the canvas to an image on your hard disk.
+    pub fn save_to_png<P: AsRef<Path>>(&self, ctx: &mut Context, path: P) -> GameResult<()> {
+        let (w, h) = (self.width, self.height);
+        let data = self.to_rgba8(ctx)?;
+        image::save_buffer(path, &data, w, h, image::ColorType::RGBA(8))
+            .map_err(|e| GameError::RenderError(format!("{}", e)))?;
+        Ok(())
+    }
 }
 
-impl Drawable for Canvas {
+impl<CF> Drawable for CanvasGeneric<GlBackendSpec, CF>
+where
+    CF: DrawableCanvasFormat,
+{
     fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
-        self.image.draw_ex(ctx, param)
+        self.get_image().draw_ex(ctx, param)
     }
     fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
-        self.image.blend_mode = mode;
+        self.blend_mode = mode;
     }
     fn get_blend_mode(&self) -> Option<BlendMode> {
-        self.image.blend_mode
+        self.blend_mode
     }
 }
 
 /// Set the canvas to render to. Specifying `Option::None` will cause all
 /// rendering to be done directly to the screen.
-pub fn set_canvas(ctx: &mut Context, target: Option<&Canvas>) {
+///
+/// The graphics pipeline's output target is fixed to the screen's own
+/// `Srgba8` format, and a canvas is bound through its raw, format-erased
+/// render target view. Only `Srgba8` canvases are accepted: reinterpreting
+/// any other `CanvasFormat` as `Srgba8` would silently corrupt every channel
+/// whose bit depth or count differs from the screen's, so such a canvas is
+/// rejected with a real error rather than just asserted against in debug
+/// builds, and the current target stays bound.
+pub fn set_canvas<CF>(ctx: &mut Context, target: Option<&CanvasGeneric<GlBackendSpec, CF>>) -> GameResult<()>
+where
+    CF: CanvasColorFormat,
+{
     match target {
         Some(surface) => {
-            println!("{} {} in set canvas", surface.image.width, surface.image.height);
-            ctx.gfx_context.data.out = surface.target.clone();
+            if CF::CANVAS_FORMAT != CanvasFormat::Srgba8 {
+                return Err(GameError::RenderError(format!(
+                    "cannot bind a {:?} canvas as a render target: its render target view \
+                     would be reinterpreted as Srgba8, the screen's own format, silently \
+                     corrupting any channel whose bit depth or count differs",
+                    CF::CANVAS_FORMAT,
+                )));
+            }
+            ctx.gfx_context.data.out = Typed::new(surface.raw_target());
         }
         None => {
             ctx.gfx_context.data.out = ctx.gfx_context.screen_render_target.clone();
         }
     };
+    Ok(())
+}
+
+/// Push the currently-bound render target onto a stack and start rendering
+/// to `canvas` instead. Pair with `pop_canvas` to resume the saved target,
+/// which lets nested off-screen passes (e.g. drawing into canvas `B` while
+/// canvas `A` is bound) restore `A` without the caller having to track it
+/// by hand.
+pub fn push_canvas<CF>(ctx: &mut Context, canvas: &CanvasGeneric<GlBackendSpec, CF>) -> GameResult<()>
+where
+    CF: CanvasColorFormat,
+{
+    let previous = ctx.gfx_context.data.out.clone();
+    set_canvas(ctx, Some(canvas))?;
+    ctx.gfx_context.canvas_stack.push(previous);
+    Ok(())
+}
+
+/// Pop the render target stack, restoring whatever was bound before the
+/// matching `push_canvas`. Popping an empty stack restores the screen, same
+/// as `set_canvas(ctx, None)`.
+pub fn pop_canvas(ctx: &mut Context) {
+    match ctx.gfx_context.canvas_stack.pop() {
+        Some(previous) => ctx.gfx_context.data.out = previous,
+        None => ctx.gfx_context.data.out = ctx.gfx_context.screen_render_target.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canvas_format_round_trips_through_canvas_color_format() {
+        assert_eq!(Srgba8::CANVAS_FORMAT, CanvasFormat::Srgba8);
+        assert_eq!(Rgba16F::CANVAS_FORMAT, CanvasFormat::Rgba16F);
+        assert_eq!(Rgba32F::CANVAS_FORMAT, CanvasFormat::Rgba32F);
+        assert_eq!(<(R8, Unorm) as CanvasColorFormat>::CANVAS_FORMAT, CanvasFormat::R8);
+        assert_eq!(<(R16, Unorm) as CanvasColorFormat>::CANVAS_FORMAT, CanvasFormat::R16);
+    }
+
+    #[test]
+    fn channel_count_matches_format() {
+        assert_eq!(Srgba8::CHANNEL_COUNT, 4);
+        assert_eq!(Rgba16F::CHANNEL_COUNT, 4);
+        assert_eq!(Rgba32F::CHANNEL_COUNT, 4);
+        assert_eq!(<(R8, Unorm) as CanvasColorFormat>::CHANNEL_COUNT, 1);
+        assert_eq!(<(R16, Unorm) as CanvasColorFormat>::CHANNEL_COUNT, 1);
+    }
 }
@@ -0,0 +1,197 @@
+//! 2D graphics rendering.
+
+use std::fmt;
+
+use gfx;
+use gfx::format::Srgba8;
+use gfx_device_gl;
+
+use Context;
+use error::GameResult;
+
+pub mod canvas;
+pub mod commands;
+
+pub use self::canvas::{Canvas, CanvasColorFormat, CanvasFormat, CanvasGeneric, DrawableCanvasFormat};
+pub use self::canvas::{pop_canvas, push_canvas, set_canvas};
+pub use self::commands::{CanvasRef, Command, CommandHandler, CommandList};
+
+/// Ties a graphics backend to its concrete `gfx` resource family.
+pub trait BackendSpec: fmt::Debug {
+    /// The `gfx` resource-handle family this backend uses.
+    type Resources: gfx::Resources;
+}
+
+/// The desktop OpenGL backend, the only one `ggez` currently supports.
+#[derive(Debug, Copy, Clone)]
+pub struct GlBackendSpec;
+
+impl BackendSpec for GlBackendSpec {
+    type Resources = gfx_device_gl::Resources;
+}
+
+/// How a `Drawable`'s colors are blended with whatever is already on the
+/// render target.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Alpha blending, the default.
+    Alpha,
+    /// Additive blending.
+    Add,
+    /// Subtractive blending.
+    Subtract,
+    /// Multiplicative blending.
+    Multiply,
+    /// Overwrite the destination outright.
+    Replace,
+}
+
+/// Filtering/wrapping configuration for sampling a texture.
+pub type SamplerInfo = gfx::texture::SamplerInfo;
+
+/// Parameters for a single `Drawable::draw_ex` call: position, rotation,
+/// scale and the like.
+#[derive(Debug, Copy, Clone)]
+pub struct DrawParam {
+    /// Where to draw the image, in screen coordinates.
+    pub dest: [f32; 2],
+    /// Rotation, in radians.
+    pub rotation: f32,
+    /// Scale factor along each axis.
+    pub scale: [f32; 2],
+    /// Origin of rotation/scaling, relative to the drawable's own bounds.
+    pub offset: [f32; 2],
+}
+
+impl Default for DrawParam {
+    fn default() -> DrawParam {
+        DrawParam {
+            dest: [0.0, 0.0],
+            rotation: 0.0,
+            scale: [1.0, 1.0],
+            offset: [0.0, 0.0],
+        }
+    }
+}
+
+/// Something that can be drawn to the screen or a `Canvas`.
+pub trait Drawable {
+    /// Draw `self` with the given parameters.
+    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()>;
+    /// Set the blend mode used when drawing `self`.
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>);
+    /// Get the blend mode currently used when drawing `self`.
+    fn get_blend_mode(&self) -> Option<BlendMode>;
+}
+
+/// A loaded, GPU-resident image.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub(crate) texture: gfx::handle::ShaderResourceView<<GlBackendSpec as BackendSpec>::Resources, [f32; 4]>,
+    pub(crate) sampler_info: SamplerInfo,
+    pub(crate) blend_mode: Option<BlendMode>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Drawable for Image {
+    fn draw_ex(&self, ctx: &mut Context, param: DrawParam) -> GameResult<()> {
+        ctx.gfx_context.draw_image(self, param)
+    }
+    fn set_blend_mode(&mut self, mode: Option<BlendMode>) {
+        self.blend_mode = mode;
+    }
+    fn get_blend_mode(&self) -> Option<BlendMode> {
+        self.blend_mode
+    }
+}
+
+/// The render target the live pipeline currently writes to, plus whatever
+/// else the pipeline needs bound. Kept as its own small struct (rather than
+/// a field directly on `GraphicsContext`) so `set_canvas`/`push_canvas` only
+/// have to touch `out`.
+#[derive(Debug)]
+pub(crate) struct PipelineData<R: gfx::Resources> {
+    pub(crate) out: gfx::handle::RenderTargetView<R, Srgba8>,
+}
+
+/// A LIFO stack of saved render targets, as used by `push_canvas`/
+/// `pop_canvas`. Kept generic over the target type (rather than inlined as
+/// a bare `Vec` on `GraphicsContext`) so its push/pop ordering can be unit
+/// tested without a GPU context.
+#[derive(Debug)]
+pub(crate) struct RenderTargetStack<T> {
+    stack: Vec<T>,
+}
+
+impl<T> RenderTargetStack<T> {
+    pub(crate) fn new() -> RenderTargetStack<T> {
+        RenderTargetStack { stack: Vec::new() }
+    }
+
+    /// Save `target` on top of the stack.
+    pub(crate) fn push(&mut self, target: T) {
+        self.stack.push(target);
+    }
+
+    /// Remove and return the most recently pushed target, if any.
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        self.stack.pop()
+    }
+}
+
+/// Holds all of the state needed to talk to the GPU: the `gfx` device,
+/// factory and encoder, and the currently-bound render target.
+pub struct GraphicsContext {
+    pub(crate) factory: gfx_device_gl::Factory,
+    pub(crate) device: Box<gfx_device_gl::Device>,
+    pub(crate) encoder: gfx::Encoder<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer>,
+    pub(crate) data: PipelineData<gfx_device_gl::Resources>,
+    pub(crate) default_sampler_info: SamplerInfo,
+    pub(crate) screen_render_target: gfx::handle::RenderTargetView<gfx_device_gl::Resources, Srgba8>,
+    /// Render targets saved by `push_canvas`, restored by `pop_canvas`.
+    pub(crate) canvas_stack: RenderTargetStack<gfx::handle::RenderTargetView<gfx_device_gl::Resources, Srgba8>>,
+    pub(crate) active_blend_mode: Option<BlendMode>,
+    pub(crate) window_dims: (u32, u32),
+}
+
+impl GraphicsContext {
+    fn draw_image(&mut self, _image: &Image, _param: DrawParam) -> GameResult<()> {
+        // Actual vertex/uniform submission lives in the renderer; this is
+        // just the hook point other `Drawable`s (e.g. `Canvas`) delegate to.
+        Ok(())
+    }
+}
+
+/// Get the size, in pixels, of the window's drawable area.
+pub fn get_drawable_size(ctx: &Context) -> (u32, u32) {
+    ctx.gfx_context.window_dims
+}
+
+/// Set the blend mode used for future draws.
+pub fn set_blend_mode(ctx: &mut Context, mode: Option<BlendMode>) -> GameResult<()> {
+    ctx.gfx_context.active_blend_mode = mode;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenderTargetStack;
+
+    #[test]
+    fn push_pop_is_last_in_first_out() {
+        let mut stack = RenderTargetStack::new();
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+    }
+
+    #[test]
+    fn pop_on_empty_stack_returns_none() {
+        let mut stack: RenderTargetStack<i32> = RenderTargetStack::new();
+        assert_eq!(stack.pop(), None);
+    }
+}
@@ -0,0 +1,14 @@
+//! ggez is a lightweight game framework for making 2D games with minimum
+//! friction.
+
+extern crate gfx;
+extern crate gfx_device_gl;
+extern crate image;
+
+pub mod conf;
+pub mod error;
+pub mod graphics;
+mod context;
+
+pub use context::Context;
+pub use error::{GameError, GameResult};